@@ -0,0 +1,218 @@
+use crate::AsyncRead;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// A future boxed for storage in [`Body`], which must stay `Send + Sync`
+/// regardless of what kind of reader or interim write it's wrapping.
+type PendingWrite = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// Hands back the interim `100 Continue` write the first time the [`Body`]
+/// it's attached to is polled for data.
+///
+/// This is how a request carrying `Expect: 100-continue` gets its interim
+/// `100 Continue` line sent at the right moment: only once a handler
+/// actually starts reading the body, not eagerly on arrival. A handler
+/// that rejects an oversized upload by inspecting headers and returning a
+/// `4xx` without ever reading the body never fires it, so the client is
+/// never told to start streaming data nobody wants.
+///
+/// The write is returned as a future rather than fired off in the
+/// background, so `Body::poll_read` can drive it to completion *before*
+/// yielding any body bytes - that's what keeps it from ever interleaving
+/// with or trailing behind the final response on the wire.
+#[derive(Clone, Default)]
+pub(crate) struct ContinueTrigger {
+    armed: Arc<AtomicBool>,
+    fired: Arc<AtomicBool>,
+    send: Option<Arc<dyn Fn() -> PendingWrite + Send + Sync>>,
+}
+
+impl ContinueTrigger {
+    pub(crate) fn new<F, Fut>(send: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + Sync + 'static,
+    {
+        ContinueTrigger {
+            armed: Arc::new(AtomicBool::new(false)),
+            fired: Arc::new(AtomicBool::new(false)),
+            send: Some(Arc::new(move || Box::pin(send()) as PendingWrite)),
+        }
+    }
+
+    pub(crate) fn arm(&self) {
+        self.armed.store(true, Ordering::SeqCst);
+    }
+
+    /// The interim write to await, if this is the first poll since being
+    /// armed. `None` if unarmed or already sent.
+    fn take_send(&self) -> Option<PendingWrite> {
+        if !self.armed.load(Ordering::SeqCst) {
+            return None;
+        }
+        if self.fired.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        self.send.as_ref().map(|send| send())
+    }
+}
+
+enum Inner {
+    Bytes(Vec<u8>, usize),
+    Reader(Pin<Box<dyn AsyncRead + Send + Sync>>),
+}
+
+/// The body of a request or response.
+pub struct Body {
+    inner: Inner,
+    continue_trigger: ContinueTrigger,
+    pending_continue: Option<PendingWrite>,
+}
+
+impl Body {
+    /// An empty body, e.g. for a response with no content.
+    pub fn empty() -> Self {
+        Vec::new().into()
+    }
+
+    /// Wrap a streamed reader as a body, with a trigger the h1 layer can
+    /// arm to emit an interim `100 Continue` on first read.
+    pub(crate) fn from_reader<R>(reader: R, continue_trigger: ContinueTrigger) -> Self
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        Body {
+            inner: Inner::Reader(Box::pin(reader)),
+            continue_trigger,
+            pending_continue: None,
+        }
+    }
+
+    /// Arm this body's `Expect: 100-continue` trigger, so it fires the
+    /// interim response the next time something polls this body for data.
+    ///
+    /// A no-op on bodies that weren't constructed with a trigger (e.g. the
+    /// in-memory bodies built from a string), since those never need one.
+    pub(crate) fn arm_continue_trigger(&mut self) {
+        self.continue_trigger.arm();
+    }
+
+    pub(crate) fn as_bytes(&self) -> Option<&[u8]> {
+        match &self.inner {
+            Inner::Bytes(bytes, _) => Some(bytes),
+            Inner::Reader(_) => None,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body {
+            inner: Inner::Bytes(bytes, 0),
+            continue_trigger: ContinueTrigger::default(),
+            pending_continue: None,
+        }
+    }
+}
+
+impl From<String> for Body {
+    fn from(s: String) -> Self {
+        s.into_bytes().into()
+    }
+}
+
+impl From<&str> for Body {
+    fn from(s: &str) -> Self {
+        s.as_bytes().to_vec().into()
+    }
+}
+
+impl AsyncRead for Body {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.pending_continue.is_none() {
+            self.pending_continue = self.continue_trigger.take_send();
+        }
+        if let Some(send) = self.pending_continue.as_mut() {
+            match send.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(()) => self.pending_continue = None,
+            }
+        }
+        match &mut self.inner {
+            Inner::Bytes(bytes, pos) => {
+                let remaining = &bytes[*pos..];
+                let n = remaining.len().min(buf.len());
+                buf[..n].copy_from_slice(&remaining[..n]);
+                *pos += n;
+                Poll::Ready(Ok(n))
+            }
+            Inner::Reader(r) => r.as_mut().poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::io::AsyncReadExt;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn arming_an_in_memory_body_is_a_harmless_no_op() {
+        let mut body = Body::from("hello");
+        body.arm_continue_trigger();
+        assert_eq!(body.as_bytes(), Some(b"hello".as_ref()));
+    }
+
+    #[test]
+    fn trigger_fires_exactly_once_on_first_poll_when_armed() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        let trigger = ContinueTrigger::new(move || {
+            let counted = counted.clone();
+            async move {
+                counted.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        trigger.arm();
+
+        let mut body = Body::from_reader(Body::from(b"chunk".to_vec()), trigger);
+
+        crate::AsyncRuntime::current().block_on(async {
+            let mut buf = [0u8; 16];
+            let _ = body.read(&mut buf).await.unwrap();
+            let _ = body.read(&mut buf).await.unwrap();
+        });
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn trigger_never_fires_when_unarmed() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counted = count.clone();
+        let trigger = ContinueTrigger::new(move || {
+            let counted = counted.clone();
+            async move {
+                counted.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        let mut body = Body::from_reader(Body::from(b"chunk".to_vec()), trigger);
+
+        crate::AsyncRuntime::current().block_on(async {
+            let mut buf = [0u8; 16];
+            let _ = body.read(&mut buf).await.unwrap();
+        });
+
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::h1;
+use crate::Body;
 use std::fmt;
 use std::io;
 
@@ -15,6 +16,9 @@ pub enum Error {
     Http11Parser(httparse::Error),
     H2(h2::Error),
     Http(http::Error),
+    /// The client was too slow: it didn't finish sending the request head
+    /// or body within the server's configured timeout.
+    Timeout(String),
     #[cfg(feature = "tls")]
     TlsError(TLSError),
     #[cfg(feature = "tls")]
@@ -37,12 +41,44 @@ impl Error {
     }
 
     pub fn is_timeout(&self) -> bool {
-        if let Error::Io(e) = self {
-            if e.kind() == io::ErrorKind::TimedOut {
-                return true;
+        match self {
+            Error::Timeout(_) => true,
+            Error::Io(e) => e.kind() == io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+
+    /// The status code a connection-level failure should be reported to
+    /// the client as, if the connection is still in a state to say anything
+    /// at all.
+    pub(crate) fn status_code(&self) -> http::StatusCode {
+        match self {
+            Error::Http11Parser(_) | Error::Proto(_) | Error::Http(_) => {
+                http::StatusCode::BAD_REQUEST
+            }
+            Error::Timeout(_) => http::StatusCode::REQUEST_TIMEOUT,
+            Error::Io(e) if e.kind() == io::ErrorKind::TimedOut => {
+                http::StatusCode::REQUEST_TIMEOUT
             }
+            #[cfg(feature = "tls")]
+            Error::TlsError(_) | Error::DnsName(_) => http::StatusCode::BAD_REQUEST,
+            _ => http::StatusCode::INTERNAL_SERVER_ERROR,
         }
-        false
+    }
+
+    /// Turn a connection-level error into the response the client should
+    /// see instead of the socket just closing: a proper status code and a
+    /// short diagnostic body derived from `Display`, which is already
+    /// curated not to leak internals.
+    ///
+    /// This is the single place h1 decode errors, proto errors and TLS
+    /// handshake errors get mapped to a status, so both the h1 dispatch and
+    /// the router report them the same way.
+    pub(crate) fn into_response(&self) -> http::Response<Body> {
+        http::Response::builder()
+            .status(self.status_code())
+            .body(Body::from(self.to_string()))
+            .expect("status and body to build a valid response")
     }
 
     pub(crate) fn is_retryable(&self) -> bool {
@@ -68,6 +104,7 @@ impl fmt::Display for Error {
             Error::Http11Parser(v) => write!(f, "http11 parser: {}", v),
             Error::H2(v) => write!(f, "http2: {}", v),
             Error::Http(v) => write!(f, "http api: {}", v),
+            Error::Timeout(v) => write!(f, "timeout: {}", v),
             #[cfg(feature = "tls")]
             Error::TlsError(v) => write!(f, "tls: {}", v),
             #[cfg(feature = "tls")]
@@ -0,0 +1,391 @@
+use crate::body::ContinueTrigger;
+use crate::server::Router;
+use crate::{AsyncRead, AsyncRuntime, AsyncWrite, Body, Error};
+use futures_util::future;
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use futures_util::FutureExt;
+use http::{Request, Response};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+const MAX_HEADERS: usize = 64;
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Server-side timeouts for a single HTTP/1.1 connection.
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    /// Max time to wait for the *next* request on an already-open
+    /// keep-alive connection. Expiring this just closes the connection -
+    /// an idle client isn't misbehaving.
+    pub idle: Duration,
+    /// Once a request has started arriving, the max time it may take to
+    /// finish sending the whole request head. Expiring this is a
+    /// slow/stalled client and gets a `408 Request Timeout`.
+    pub client: Duration,
+    /// Once the handler starts streaming the request body, the max time
+    /// any single read off the wire may stall for. A client that goes
+    /// quiet mid-body trips this and gets a `408 Request Timeout` the same
+    /// way one that dribbles the head does - see `TimeoutRead` and its use
+    /// in `serve_conn`.
+    pub body: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            idle: Duration::from_secs(90),
+            client: Duration::from_secs(10),
+            body: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Parse as much of an HTTP/1.1 request head as `buf` holds.
+///
+/// Pure and allocation-light on purpose: the read loop around it needs a
+/// live connection, but this part doesn't, so it can be unit tested
+/// without any I/O.
+fn parse_head(buf: &[u8]) -> Result<Option<(Request<()>, usize)>, Error> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut parsed = httparse::Request::new(&mut headers);
+
+    let head_len = match parsed.parse(buf).map_err(Error::Http11Parser)? {
+        httparse::Status::Complete(n) => n,
+        httparse::Status::Partial => return Ok(None),
+    };
+
+    let method = parsed
+        .method
+        .ok_or_else(|| Error::Proto("request head missing method".into()))?;
+    let path = parsed
+        .path
+        .ok_or_else(|| Error::Proto("request head missing path".into()))?;
+    let version = match parsed.version {
+        // httparse's 0/1 map to the HTTP/1.0 and HTTP/1.1 request lines;
+        // anything else it would already have rejected during `parse`.
+        Some(0) => http::Version::HTTP_10,
+        Some(1) => http::Version::HTTP_11,
+        _ => {
+            return Err(Error::Proto(
+                "request head missing or unsupported HTTP version".into(),
+            ))
+        }
+    };
+
+    let mut builder = Request::builder().method(method).uri(path).version(version);
+    for h in parsed.headers.iter() {
+        builder = builder.header(h.name, h.value);
+    }
+    let req = builder.body(()).map_err(Error::Http)?;
+
+    Ok(Some((req, head_len)))
+}
+
+/// Read and parse a request head off `io`, returning it along with
+/// whatever trailing bytes (the start of the body) were read past it.
+/// `None` means the connection went idle (no new request, or the client
+/// closed it) within `timeouts.idle` and should just be closed quietly.
+///
+/// The *first* byte of a new request is bounded by `timeouts.idle`. Once
+/// it arrives, a single deadline `timeouts.client` out is set for the
+/// *entire rest of the head* - not re-armed per read - so a client
+/// trickling in one byte at a time can't dodge it by staying just under
+/// the per-read gap. Blowing through that deadline is a slow client,
+/// reported as [`Error::Timeout`] so the caller can answer `408` instead
+/// of hanging the connection indefinitely.
+async fn read_head<IO>(
+    io: &mut IO,
+    timeouts: Timeouts,
+) -> Result<Option<(Request<()>, Vec<u8>)>, Error>
+where
+    IO: AsyncRead + Unpin,
+{
+    let mut buf = Vec::with_capacity(512);
+    let mut chunk = [0u8; 512];
+    let mut deadline: Option<Instant> = None;
+
+    loop {
+        let budget = match deadline {
+            None => timeouts.idle,
+            Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+        };
+
+        let n = match AsyncRuntime::current().timeout(budget, io.read(&mut chunk)).await {
+            Ok(n) => n.map_err(Error::Io)?,
+            Err(_elapsed) if deadline.is_none() => return Ok(None),
+            Err(_elapsed) => {
+                return Err(Error::Timeout(
+                    "client did not finish sending the request head in time".into(),
+                ))
+            }
+        };
+        if n == 0 {
+            return Ok(None);
+        }
+        if deadline.is_none() {
+            deadline = Some(Instant::now() + timeouts.client);
+        }
+
+        buf.extend_from_slice(&chunk[..n]);
+        if buf.len() > MAX_HEAD_BYTES {
+            return Err(Error::Proto("request head exceeded the size limit".into()));
+        }
+        if let Some((req, head_len)) = parse_head(&buf)? {
+            let rest = buf.split_off(head_len);
+            return Ok(Some((req, rest)));
+        }
+    }
+}
+
+async fn write_response<IO>(io: &mut IO, res: &mut Response<Body>) -> io::Result<()>
+where
+    IO: AsyncWrite + Unpin,
+{
+    let status = res.status();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("")
+    );
+    for (name, value) in res.headers() {
+        head.push_str(name.as_str());
+        head.push_str(": ");
+        head.push_str(value.to_str().unwrap_or(""));
+        head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+    io.write_all(head.as_bytes()).await?;
+
+    // An in-memory body can go straight out in one write; a streamed one
+    // has no bytes to hand back up front, so it has to be drained through
+    // its `AsyncRead` impl instead - otherwise a streaming response would
+    // go out with correct headers and a silently empty body.
+    if let Some(bytes) = res.body().as_bytes() {
+        io.write_all(bytes).await?;
+    } else {
+        let mut chunk = [0u8; 8 * 1024];
+        loop {
+            let n = res.body_mut().read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            io.write_all(&chunk[..n]).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Feeds the bytes already read off the wire while parsing the head,
+/// followed by whatever's still unread on the underlying connection - so
+/// the request body picks up exactly where head parsing left off.
+struct LeftoverThenIo<IO> {
+    leftover: Vec<u8>,
+    pos: usize,
+    io: IO,
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for LeftoverThenIo<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.pos < this.leftover.len() {
+            let remaining = &this.leftover[this.pos..];
+            let n = remaining.len().min(buf.len());
+            buf[..n].copy_from_slice(&remaining[..n]);
+            this.pos += n;
+            return Poll::Ready(Ok(n));
+        }
+        Pin::new(&mut this.io).poll_read(cx, buf)
+    }
+}
+
+/// Bounds every underlying read of `io` by `timeout`: once a single read
+/// stalls past it, hand back an `ErrorKind::TimedOut` instead of leaving
+/// the worker blocked on a client that never finishes sending its body.
+/// This is the "slow-request/body timeout" half of [`Timeouts`]; the head
+/// itself is bounded by `timeouts.client` in `read_head`.
+///
+/// Built from the same `AsyncRuntime::timeout` combinator `read_head`
+/// uses, just raced against the inner read instead of wrapping a single
+/// one-shot call, since a body reader is polled an unknown number of
+/// times over the life of the request.
+///
+/// The `TimedOut` error it produces surfaces to the handler like any other
+/// body-read error, not as a `408` - `serve_conn` watches `timed_out`
+/// after the handler returns and answers `408` itself if it was ever set,
+/// the same way `read_head` reports a stalled head via [`Error::Timeout`].
+struct TimeoutRead<IO> {
+    io: IO,
+    timeout: Duration,
+    sleep: Option<Pin<Box<dyn Future<Output = ()> + Send + Sync>>>,
+    timed_out: Arc<AtomicBool>,
+}
+
+impl<IO> TimeoutRead<IO> {
+    fn new(io: IO, timeout: Duration, timed_out: Arc<AtomicBool>) -> Self {
+        TimeoutRead {
+            io,
+            timeout,
+            sleep: None,
+            timed_out,
+        }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for TimeoutRead<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        match Pin::new(&mut this.io).poll_read(cx, buf) {
+            Poll::Ready(r) => {
+                this.sleep = None;
+                return Poll::Ready(r);
+            }
+            Poll::Pending => {}
+        }
+
+        let timeout = this.timeout;
+        let sleep = this.sleep.get_or_insert_with(|| {
+            // `future::pending` never resolves on its own, so the only way
+            // this completes is `timeout` elapsing - i.e. it's a bare delay
+            // built out of the combinator we already have, rather than a
+            // separate sleep primitive.
+            Box::pin(AsyncRuntime::current().timeout(timeout, future::pending::<()>()).map(|_| ()))
+        });
+
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                this.sleep = None;
+                this.timed_out.store(true, Ordering::SeqCst);
+                Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "client stalled mid-body",
+                )))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Serve one HTTP/1.1 connection: read and dispatch requests through
+/// `router` until the client goes idle past `timeouts.idle`, sends a
+/// request whose head or body stalls past `timeouts.client` /
+/// `timeouts.body` (answered with `408` before the connection closes),
+/// or closes the connection itself.
+pub(crate) async fn serve_conn<IO, State>(
+    mut io: IO,
+    router: Arc<Router<State>>,
+    state: Arc<State>,
+    timeouts: Timeouts,
+) -> Result<(), Error>
+where
+    IO: AsyncRead + AsyncWrite + Clone + Unpin + Send + Sync + 'static,
+    State: Clone + Unpin + Send + Sync + 'static,
+{
+    loop {
+        let head = match read_head(&mut io, timeouts).await {
+            Ok(head) => head,
+            Err(e) => {
+                write_response(&mut io, &mut e.into_response()).await.ok();
+                return Err(e);
+            }
+        };
+        let (parts, leftover) = match head {
+            Some(head) => head,
+            None => return Ok(()),
+        };
+        let (parts, _) = parts.into_parts();
+
+        // The interim "100 Continue" write itself happens inline from
+        // `Body::poll_read` the first time the dispatched handler actually
+        // reads the body, not eagerly - see `Router::run` (which arms
+        // this) and `Body::arm_continue_trigger`. It's awaited in-line as
+        // part of the same poll chain the handler drives, rather than
+        // fired off on a detached task, so it can never land out of order
+        // relative to the final response written below.
+        let write_io = io.clone();
+        let trigger = ContinueTrigger::new(move || {
+            let mut write_io = write_io.clone();
+            async move {
+                let _ = write_io.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await;
+            }
+        });
+
+        let reader = LeftoverThenIo {
+            leftover,
+            pos: 0,
+            io: io.clone(),
+        };
+        let body_timed_out = Arc::new(AtomicBool::new(false));
+        let reader = TimeoutRead::new(reader, timeouts.body, body_timed_out.clone());
+        let body = Body::from_reader(reader, trigger);
+        let req = Request::from_parts(parts, body);
+
+        let mut reply = router.run(state.clone(), req).await;
+        let timed_out = body_timed_out.load(Ordering::SeqCst);
+        if timed_out {
+            // Whatever `Reply` the handler produced was built from a body
+            // read that stalled out, so it isn't trustworthy - answer the
+            // same way `read_head` does for a stalled head, via the same
+            // `Error::Timeout` -> `408` mapping.
+            reply = Error::Timeout("client stalled mid-body".into())
+                .into_response()
+                .into();
+        }
+        write_response(&mut io, reply.response_mut())
+            .await
+            .map_err(Error::Io)?;
+        if timed_out {
+            // The rest of the stalled body is still unread on the wire, so
+            // the connection closes after the `408`, as for a stalled head.
+            return Err(Error::Timeout("client stalled mid-body".into()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_complete_request_head() {
+        let raw = b"GET /hello HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let (req, head_len) = parse_head(raw).unwrap().unwrap();
+        assert_eq!(req.method(), http::Method::GET);
+        assert_eq!(req.uri().path(), "/hello");
+        assert_eq!(req.version(), http::Version::HTTP_11);
+        assert_eq!(req.headers().get("host").unwrap(), "example.com");
+        assert_eq!(head_len, raw.len());
+    }
+
+    #[test]
+    fn parses_an_http_1_0_request_head() {
+        let raw = b"GET /hello HTTP/1.0\r\n\r\n";
+        let (req, _) = parse_head(raw).unwrap().unwrap();
+        assert_eq!(req.version(), http::Version::HTTP_10);
+    }
+
+    #[test]
+    fn returns_none_on_a_partial_head() {
+        let raw = b"GET /hello HTTP/1.1\r\nHost: exam";
+        assert!(parse_head(raw).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_a_malformed_request_line() {
+        let raw = b"NOT A REQUEST\r\n\r\n";
+        assert!(matches!(parse_head(raw), Err(Error::Http11Parser(_))));
+    }
+}
@@ -0,0 +1,309 @@
+use super::Reply;
+use crate::Body;
+use http::header::{
+    HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+    VARY,
+};
+use http::{Method, Request, Response};
+use std::time::Duration;
+
+#[derive(Clone, Debug)]
+enum Origins {
+    Any,
+    List(Vec<String>),
+}
+
+/// CORS handling.
+///
+/// Unlike other [`Middleware`], `Cors` is mounted at the router level via
+/// [`Router::cors`] rather than baked into a single endpoint's chain. A
+/// preflight `OPTIONS` request matching the mounted path is answered
+/// directly (`204`, with the computed CORS headers) without ever reaching
+/// a handler, even if there is no `OPTIONS` handler registered for the
+/// path - since the router answers it before looking for one.
+///
+/// # Example
+///
+/// ```
+/// use hreq::prelude::*;
+/// use hreq::server::Cors;
+///
+/// let mut router = Router::new();
+/// router.cors("/api", Cors::new().allow_origin("https://example.com"));
+/// router.at("/api").get(|_: http::Request<Body>| async { "hello" });
+///
+/// let mut server = Server::new();
+/// server.at("/").router(router);
+/// ```
+///
+/// [`Middleware`]: trait.Middleware.html
+/// [`Router::cors`]: struct.Router.html#method.cors
+#[derive(Clone, Debug)]
+pub struct Cors {
+    origins: Origins,
+    methods: Vec<Method>,
+    allowed_headers: Vec<HeaderName>,
+    exposed_headers: Vec<HeaderName>,
+    max_age: Option<Duration>,
+    credentials: bool,
+}
+
+impl Cors {
+    /// Creates a new CORS middleware that allows any origin, `GET`, `HEAD`
+    /// and `POST`, no credentials, and no exposed headers. Preflights have
+    /// their requested headers mirrored back (see [`Cors::allow_headers`]).
+    pub fn new() -> Self {
+        Cors {
+            origins: Origins::Any,
+            methods: vec![Method::GET, Method::HEAD, Method::POST],
+            allowed_headers: vec![],
+            exposed_headers: vec![],
+            max_age: None,
+            credentials: false,
+        }
+    }
+
+    /// Allow requests from the given origin, in addition to any already allowed.
+    ///
+    /// Calling this at least once switches the middleware from "allow any
+    /// origin" to "allow only these origins".
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        match &mut self.origins {
+            Origins::Any => self.origins = Origins::List(vec![origin.into()]),
+            Origins::List(list) => list.push(origin.into()),
+        }
+        self
+    }
+
+    /// Allow requests from any origin (the default).
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = Origins::Any;
+        self
+    }
+
+    /// Set the methods allowed in a preflight response.
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Set the request headers allowed in a preflight response.
+    ///
+    /// Leaving this empty (the default) mirrors back whatever headers the
+    /// preflight asked for in `Access-Control-Request-Headers`.
+    pub fn allow_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.allowed_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Set the response headers exposed to the browser via `Access-Control-Expose-Headers`.
+    pub fn expose_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.exposed_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// Set how long (`Access-Control-Max-Age`) a preflight response may be cached.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Whether to send `Access-Control-Allow-Credentials: true`.
+    ///
+    /// Per the CORS spec, this can't be combined with a wildcard
+    /// `Access-Control-Allow-Origin: *`, so enabling it makes an "allow any
+    /// origin" config echo back the request's `Origin` instead of `*`.
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    /// The single origin value to answer this request's `Origin` with, if any.
+    pub(crate) fn matched_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.origins {
+            Origins::Any => {
+                if self.credentials {
+                    Some(origin.clone())
+                } else {
+                    Some(HeaderValue::from_static("*"))
+                }
+            }
+            Origins::List(list) => {
+                let origin_str = origin.to_str().ok()?;
+                list.iter()
+                    .find(|o| o.as_str() == origin_str)
+                    .and_then(|o| HeaderValue::from_str(o).ok())
+            }
+        }
+    }
+
+    pub(crate) fn apply_common_headers(
+        &self,
+        origin: Option<&HeaderValue>,
+        headers: &mut http::HeaderMap,
+    ) {
+        let origin = match origin {
+            Some(o) => o,
+            None => return,
+        };
+
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+        // The allowed origin changes per-request, so any cache sitting in
+        // front of us must not conflate one origin's response with another's.
+        headers.append(VARY, HeaderValue::from_static("Origin"));
+
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        if !self.exposed_headers.is_empty() {
+            if let Ok(v) = HeaderValue::from_str(&join_header_names(&self.exposed_headers)) {
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, v);
+            }
+        }
+    }
+
+    pub(crate) fn preflight_response(
+        &self,
+        req: &Request<Body>,
+        origin: Option<&HeaderValue>,
+    ) -> Reply {
+        let mut res = Response::builder().status(204).body(Body::empty()).expect(
+            "building a response from a fixed status and empty body should never fail",
+        );
+        let headers = res.headers_mut();
+
+        self.apply_common_headers(origin, headers);
+
+        let methods = join_methods(&self.methods);
+        if let Ok(v) = HeaderValue::from_str(&methods) {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, v);
+        }
+
+        let allow_headers = if self.allowed_headers.is_empty() {
+            req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS).cloned()
+        } else {
+            HeaderValue::from_str(&join_header_names(&self.allowed_headers)).ok()
+        };
+        if let Some(v) = allow_headers {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, v);
+        }
+
+        if let Some(max_age) = self.max_age {
+            if let Ok(v) = HeaderValue::from_str(&max_age.as_secs().to_string()) {
+                headers.insert(ACCESS_CONTROL_MAX_AGE, v);
+            }
+        }
+
+        res.into()
+    }
+
+    pub(crate) fn is_preflight(req: &Request<Body>) -> bool {
+        req.method() == Method::OPTIONS
+            && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+fn join_header_names(headers: &[HeaderName]) -> String {
+    headers
+        .iter()
+        .map(HeaderName::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn join_methods(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_origin_without_credentials_echoes_a_wildcard() {
+        let cors = Cors::new();
+        let origin = HeaderValue::from_static("https://example.com");
+        assert_eq!(cors.matched_origin(&origin), Some(HeaderValue::from_static("*")));
+    }
+
+    #[test]
+    fn any_origin_with_credentials_echoes_the_single_origin_instead_of_a_wildcard() {
+        let cors = Cors::new().allow_credentials(true);
+        let origin = HeaderValue::from_static("https://example.com");
+        assert_eq!(cors.matched_origin(&origin), Some(origin));
+    }
+
+    #[test]
+    fn an_origin_list_echoes_only_the_single_matching_origin() {
+        let cors = Cors::new()
+            .allow_origin("https://a.example.com")
+            .allow_origin("https://b.example.com");
+
+        let matched = cors.matched_origin(&HeaderValue::from_static("https://b.example.com"));
+        assert_eq!(
+            matched,
+            Some(HeaderValue::from_static("https://b.example.com"))
+        );
+    }
+
+    #[test]
+    fn an_origin_list_rejects_an_origin_not_on_it() {
+        let cors = Cors::new().allow_origin("https://a.example.com");
+        let matched = cors.matched_origin(&HeaderValue::from_static("https://evil.example.com"));
+        assert_eq!(matched, None);
+    }
+
+    #[test]
+    fn preflight_is_detected_by_the_request_method_header() {
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        assert!(Cors::is_preflight(&req));
+    }
+
+    #[test]
+    fn a_plain_options_request_is_not_a_preflight() {
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .body(Body::empty())
+            .unwrap();
+        assert!(!Cors::is_preflight(&req));
+    }
+
+    #[test]
+    fn preflight_response_mirrors_requested_headers_when_none_are_configured() {
+        let cors = Cors::new();
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(ACCESS_CONTROL_REQUEST_HEADERS, "x-custom")
+            .body(Body::empty())
+            .unwrap();
+
+        let reply = cors.preflight_response(&req, Some(&HeaderValue::from_static("*")));
+        let res = reply.response();
+        assert_eq!(res.status(), 204);
+        assert_eq!(
+            res.headers().get(ACCESS_CONTROL_ALLOW_HEADERS).unwrap(),
+            "x-custom"
+        );
+    }
+}
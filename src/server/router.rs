@@ -1,4 +1,5 @@
 use super::chain::{Chain, End, Mid, MidWrap};
+use super::cors::Cors;
 use super::path::ParsedPath;
 use super::Reply;
 use super::Route;
@@ -25,6 +26,31 @@ impl PartialEq<http::Method> for RouteMethod {
     }
 }
 
+impl RouteMethod {
+    /// Expand to the concrete HTTP verbs this route method accepts.
+    ///
+    /// `All` widens to the standard verb set, and `GET` always implies
+    /// `HEAD` since a compliant server answers both the same way.
+    fn expand(&self) -> Vec<http::Method> {
+        use http::Method;
+        match self {
+            RouteMethod::All => vec![
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::DELETE,
+                Method::OPTIONS,
+                Method::CONNECT,
+                Method::PATCH,
+                Method::TRACE,
+            ],
+            RouteMethod::Method(m) if *m == Method::GET => vec![Method::GET, Method::HEAD],
+            RouteMethod::Method(m) => vec![m.clone()],
+        }
+    }
+}
+
 /// Encapsulate chains of [`Middleware`] and [`Handler`].
 ///
 /// Inside [`Server`] there is always a default router which is configured
@@ -62,6 +88,7 @@ impl PartialEq<http::Method> for RouteMethod {
 pub struct Router<State> {
     prefix: String,
     endpoints: Vec<Endpoint<State>>,
+    cors: Vec<(ParsedPath, Cors)>,
 }
 
 impl<State> Router<State>
@@ -73,6 +100,7 @@ where
         Router {
             prefix: "".into(),
             endpoints: vec![],
+            cors: vec![],
         }
     }
 
@@ -81,6 +109,19 @@ where
         self.prefix = prefix.into();
     }
 
+    /// Apply CORS handling to every method mounted under `path`.
+    ///
+    /// Unlike other [`Middleware`], `Cors` is mounted at the router level
+    /// rather than baked into a single endpoint's chain. That's what lets a
+    /// preflight `OPTIONS` request succeed for `path` even when `path` has
+    /// no `OPTIONS` handler of its own - see `Router::run`, which answers a
+    /// preflight before any endpoint lookup happens at all.
+    ///
+    /// [`Middleware`]: trait.Middleware.html
+    pub fn cors(&mut self, path: &str, cors: Cors) {
+        self.cors.push((ParsedPath::parse(path), cors));
+    }
+
     /// Configure an route for this server.
     ///
     /// A route is a chain of zero or more [`Middleware`]
@@ -119,31 +160,125 @@ where
         state: Arc<State>,
         mut req: Request<Body>,
     ) -> impl Future<Output = Reply> + Send + 'a {
-        let uri = req.uri();
-        let full_path = uri.path();
+        async move {
+            let full_path = req.uri().path().to_string();
 
-        assert!(full_path.starts_with(&self.prefix));
-        let path = full_path.replacen(&self.prefix, "", 1);
+            // A router only ever gets called for paths under its own prefix
+            // (see `Server::at`/`Route::router`); seeing anything else is a
+            // routing bug upstream of here, not a malformed request from the
+            // client. Report it the same way any other connection-level
+            // error is reported instead of panicking the connection task.
+            let path = match full_path.strip_prefix(self.prefix.as_str()) {
+                Some(path) => path.to_string(),
+                None => {
+                    let err = crate::Error::Proto(format!(
+                        "path {:?} does not start with router prefix {:?}",
+                        full_path, self.prefix
+                    ));
+                    return err.into_response().into();
+                }
+            };
+
+            // A CORS-mounted path is handled uniformly across every method
+            // under it, including ones with no endpoint registered at all -
+            // which is exactly what a preflight `OPTIONS` needs, since it
+            // asks the router what's allowed rather than asking a handler to
+            // run. Answering it here, before any endpoint lookup, means it
+            // no longer depends on an `OPTIONS` handler existing for `path`.
+            let cors = self
+                .cors
+                .iter()
+                .find(|(p, _)| p.path_match(&path).is_some())
+                .map(|(_, c)| c);
+            let origin = req.headers().get(http::header::ORIGIN).cloned();
+            let matched_origin =
+                cors.and_then(|c| origin.as_ref().and_then(|o| c.matched_origin(o)));
+
+            if let Some(cors) = cors {
+                if Cors::is_preflight(&req) {
+                    return cors.preflight_response(&req, matched_origin.as_ref());
+                }
+            }
+
+            // HTTP/2 has no wire equivalent of an interim response, so only arm
+            // the trigger for HTTP/1.1. Arming it here (rather than eagerly
+            // writing "100 Continue" straight away) lets a handler that never
+            // reads the body - because it rejects the request outright - avoid
+            // telling the client to start streaming it.
+            if req.version() == http::Version::HTTP_11 && is_continue_expected(req.headers()) {
+                req.headers_mut().remove(http::header::EXPECT);
+                req.body_mut().arm_continue_trigger();
+            }
+
+            // Methods seen on endpoints whose path matched, collected so we can
+            // tell "no such path" (404) apart from "path matched, wrong verb" (405).
+            let mut allowed: Vec<http::Method> = vec![];
+            let mut reply = None;
 
-        async move {
             for ep in &self.endpoints {
-                if &ep.method != req.method() {
+                let m = match ep.path.path_match(&path) {
+                    Some(m) => m,
+                    None => continue,
+                };
+
+                // Dispatch on the expanded verb set, not a literal method
+                // match, so e.g. a `HEAD` request lands on a `GET` endpoint
+                // the same way `RouteMethod::expand`'s doc promises - rather
+                // than bouncing off as "wrong verb" and landing in `allowed`.
+                let accepted = ep.method.expand();
+                if !accepted.contains(req.method()) {
+                    for method in accepted {
+                        if !allowed.contains(&method) {
+                            allowed.push(method);
+                        }
+                    }
                     continue;
                 }
-                let m = ep.path.path_match(&path);
+
                 trace!("Found endpoint: {:?}", ep);
-                if let Some(m) = m {
-                    req.extensions_mut().insert(m);
-                    return ep.chain.run(state, req).await;
+                req.extensions_mut().insert(m);
+                reply = Some(ep.chain.run(state, req).await);
+                break;
+            }
+
+            let mut reply = match reply {
+                Some(reply) => reply,
+                None if !allowed.is_empty() => {
+                    trace!("Path matched, but no method matched. Allow: {:?}", allowed);
+                    let allow = allowed
+                        .iter()
+                        .map(http::Method::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    Response::builder()
+                        .status(405)
+                        .header("Allow", allow)
+                        .body("Method Not Allowed")
+                        .into()
+                }
+                None => {
+                    trace!("No endpoint");
+                    Response::builder().status(404).body("Not found").into()
                 }
+            };
+
+            if let Some(cors) = cors {
+                let headers = reply.response_mut().headers_mut();
+                cors.apply_common_headers(matched_origin.as_ref(), headers);
             }
-            trace!("No endpoint");
-            Response::builder().status(404).body("Not found").into()
+            reply
         }
         .instrument(trace_span!("router_run"))
     }
 }
 
+fn is_continue_expected(headers: &http::HeaderMap) -> bool {
+    headers
+        .get(http::header::EXPECT)
+        .map(|v| v.as_bytes().eq_ignore_ascii_case(b"100-continue"))
+        .unwrap_or(false)
+}
+
 #[derive(Clone)]
 struct Endpoint<State> {
     method: RouteMethod,
@@ -180,3 +315,78 @@ impl<State> fmt::Debug for Router<State> {
         write!(f, "Router")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN};
+    use http::Method;
+
+    #[test]
+    fn preflight_to_a_path_with_no_options_handler_still_gets_204() {
+        let mut router: Router<()> = Router::new();
+        router.cors("/widgets", Cors::new().allow_origin("https://example.com"));
+        // Note: no endpoint of any kind is registered for "/widgets" - the
+        // bug this guards against was that a preflight only short-circuited
+        // when it happened to match an endpoint's own middleware chain, so
+        // a path with no `OPTIONS` handler (or, as here, no handler at all)
+        // fell through to 404/405 instead of a CORS `204`.
+
+        let req = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/widgets")
+            .header(ORIGIN, "https://example.com")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(Body::empty())
+            .unwrap();
+
+        let reply = crate::AsyncRuntime::current().block_on(router.run(Arc::new(()), req));
+        let res = reply.response();
+        assert_eq!(res.status(), 204);
+        assert_eq!(
+            res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn all_expands_to_the_standard_verb_set_including_head() {
+        let methods = RouteMethod::All.expand();
+        assert!(methods.contains(&Method::GET));
+        assert!(methods.contains(&Method::HEAD));
+        assert!(methods.contains(&Method::POST));
+        assert!(methods.contains(&Method::DELETE));
+    }
+
+    #[test]
+    fn get_expands_to_get_and_head() {
+        let methods = RouteMethod::Method(Method::GET).expand();
+        assert_eq!(methods, vec![Method::GET, Method::HEAD]);
+    }
+
+    #[test]
+    fn other_methods_expand_to_themselves_only() {
+        let methods = RouteMethod::Method(Method::DELETE).expand();
+        assert_eq!(methods, vec![Method::DELETE]);
+    }
+
+    #[test]
+    fn continue_expected_is_case_insensitive() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::EXPECT, "100-Continue".parse().unwrap());
+        assert!(is_continue_expected(&headers));
+    }
+
+    #[test]
+    fn continue_not_expected_without_the_header() {
+        let headers = http::HeaderMap::new();
+        assert!(!is_continue_expected(&headers));
+    }
+
+    #[test]
+    fn continue_not_expected_for_a_different_expect_value() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::EXPECT, "gzip".parse().unwrap());
+        assert!(!is_continue_expected(&headers));
+    }
+}